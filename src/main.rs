@@ -1,25 +1,52 @@
-use arrow::array::{ArrayBuilder, StringBuilder, TimestampMillisecondBuilder, UInt64Builder};
+use arrow::array::{
+    Array, ArrayBuilder, StringArray, StringBuilder, TimestampMillisecondArray,
+    TimestampMillisecondBuilder, UInt64Array, UInt64Builder,
+};
 use arrow::datatypes::{DataType, Field, Schema};
 use arrow::record_batch::RecordBatch;
-use chrono::{DateTime, Utc};
+use aws_config::BehaviorVersion;
+use aws_credential_types::provider::SharedCredentialsProvider;
+use aws_credential_types::Credentials;
+use aws_sdk_s3::config::Region;
+use aws_sdk_s3::primitives::ByteStream;
+use aws_sdk_s3::types::{CompletedMultipartUpload, CompletedPart, Object};
+use aws_sdk_s3::Client as S3Client;
+use chrono::{DateTime, Duration, Utc};
 use clap::Parser;
+use futures::stream::{self, StreamExt};
+use glob::Pattern;
 use indicatif::{ProgressBar, ProgressStyle};
+use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
 use parquet::arrow::ArrowWriter;
 use parquet::file::properties::WriterProperties;
-use rusoto_core::credential::{ChainProvider, StaticProvider};
-use rusoto_core::ByteStream;
-use rusoto_core::{HttpClient, Region};
-use rusoto_s3::{ListObjectsV2Request, Object, PutObjectRequest, S3Client, S3};
+use regex::Regex;
+use std::collections::{HashMap, VecDeque};
 use std::error::Error;
 use std::fs::File;
+use std::future::Future;
 use std::io::Read;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
 use std::time::Instant;
 use tempfile::NamedTempFile;
+use tokio::sync::mpsc::{self, UnboundedSender};
+use tokio::sync::{Mutex as AsyncMutex, Notify};
 use tokio_retry::strategy::{jitter, ExponentialBackoff};
 use tokio_retry::Retry;
 use url::Url;
 
+/// Once a prefix's own (non-recursive) object count reaches this, its sub-prefixes are
+/// handed back to the shared work queue instead of being walked inline by the same worker.
+const SPLIT_THRESHOLD: usize = 50_000;
+
+/// Bounds how many `GetObjectTagging` calls a single `list_level` page issues at once when
+/// `--fetch-tags` is set. This is its own axis of concurrency, independent of
+/// `--concurrency` (which only bounds prefix-level parallelism).
+const TAG_FETCH_CONCURRENCY: usize = 16;
+
+type BoxError = Box<dyn Error + Send + Sync>;
+
 #[derive(Parser, Debug)]
 #[clap(author, version, about = "Generates a Parquet manifest file for an S3 bucket", long_about = None)]
 struct Args {
@@ -42,6 +69,52 @@ struct Args {
     #[clap(short, long, default_value = "/")]
     delimiter: String,
 
+    /// Number of concurrent prefix-listing workers to use when fanning out the scan
+    #[clap(long, default_value_t = 8)]
+    concurrency: usize,
+
+    /// Only include objects at least this many bytes in size
+    #[clap(long = "min-size")]
+    min_size: Option<u64>,
+
+    /// Only include objects at most this many bytes in size
+    #[clap(long = "max-size")]
+    max_size: Option<u64>,
+
+    /// Only include objects last modified at or after this time (RFC3339, or relative like "7d")
+    #[clap(long = "modified-after")]
+    modified_after: Option<String>,
+
+    /// Only include objects last modified at or before this time (RFC3339, or relative like "7d")
+    #[clap(long = "modified-before")]
+    modified_before: Option<String>,
+
+    /// Only include objects whose FileName matches this glob pattern (e.g. "*.parquet")
+    #[clap(long = "name-glob")]
+    name_glob: Option<String>,
+
+    /// Only include objects whose FileName matches this regular expression
+    #[clap(long = "name-regex")]
+    name_regex: Option<String>,
+
+    /// Only include objects whose full Key matches this regular expression
+    #[clap(long = "key-regex")]
+    key_regex: Option<String>,
+
+    /// Path or S3 URI of a previously generated Parquet manifest to diff against. Adds a
+    /// ChangeType column (Added/Modified/Unchanged/Deleted) to the output.
+    #[clap(long)]
+    baseline: Option<String>,
+
+    /// When used with --baseline, omit Unchanged rows from the output
+    #[clap(long = "changes-only")]
+    changes_only: bool,
+
+    /// Fetch each object's tag set via GetObjectTagging and add it as a Tags column.
+    /// Adds one extra request per object, so it's off by default.
+    #[clap(long = "fetch-tags")]
+    fetch_tags: bool,
+
     /// AWS Access Key ID for the source bucket
     #[clap(long = "source-access-key")]
     source_access_key: Option<String>,
@@ -60,10 +133,11 @@ struct Args {
 }
 
 #[tokio::main]
-async fn main() -> Result<(), Box<dyn Error>> {
+async fn main() -> Result<(), BoxError> {
     let args = Args::parse();
     let (bucket, prefix) = parse_s3_uri(&args.s3_uri)?;
     let (output_bucket, output_key) = parse_output_location(&args.output)?;
+    let filter = ObjectFilter::from_args(&args)?;
 
     generate_manifest(
         &bucket,
@@ -72,6 +146,11 @@ async fn main() -> Result<(), Box<dyn Error>> {
         args.dest_endpoint,
         prefix,
         &args.delimiter,
+        args.concurrency.max(1),
+        filter,
+        args.baseline,
+        args.changes_only,
+        args.fetch_tags,
         output_bucket,
         output_key,
         args.source_access_key,
@@ -83,7 +162,7 @@ async fn main() -> Result<(), Box<dyn Error>> {
     Ok(())
 }
 
-fn parse_s3_uri(uri: &str) -> Result<(String, Option<String>), Box<dyn Error>> {
+fn parse_s3_uri(uri: &str) -> Result<(String, Option<String>), BoxError> {
     let parsed_url = Url::parse(uri)?;
 
     if parsed_url.scheme() != "s3" {
@@ -103,7 +182,7 @@ fn parse_s3_uri(uri: &str) -> Result<(String, Option<String>), Box<dyn Error>> {
     Ok((bucket, prefix))
 }
 
-fn parse_output_location(output: &str) -> Result<(Option<String>, String), Box<dyn Error>> {
+fn parse_output_location(output: &str) -> Result<(Option<String>, String), BoxError> {
     if output.starts_with("s3://") {
         let parsed_url = Url::parse(output)?;
         let bucket = parsed_url
@@ -117,6 +196,445 @@ fn parse_output_location(output: &str) -> Result<(Option<String>, String), Box<d
     }
 }
 
+/// Loads a previously generated Parquet manifest (local path or S3 URI) into a
+/// `Key -> (Size, LastModified)` map, used to classify the current listing as
+/// Added/Modified/Unchanged/Deleted in `--baseline` mode. A local path is read straight
+/// off disk via `File`'s `ChunkReader` impl rather than buffered into memory first; the
+/// S3 case still has to collect the body, since there's no cheaper way to hand a
+/// streaming S3 body to the Parquet reader.
+async fn load_baseline(
+    s3_client: &S3Client,
+    baseline_location: &str,
+) -> Result<HashMap<String, (u64, i64)>, BoxError> {
+    if baseline_location.starts_with("s3://") {
+        let (bucket, key) = parse_output_location(baseline_location)?;
+        let bucket = bucket.ok_or("baseline S3 URI is missing a bucket")?;
+        let response = s3_client.get_object().bucket(bucket).key(key).send().await?;
+        let bytes = response.body.collect().await?.into_bytes();
+        read_baseline_rows(ParquetRecordBatchReaderBuilder::try_new(bytes)?.build()?)
+    } else {
+        let file = File::open(baseline_location)?;
+        read_baseline_rows(ParquetRecordBatchReaderBuilder::try_new(file)?.build()?)
+    }
+}
+
+/// Drains a Parquet reader's `Key`/`Size`/`LastModified` columns into the baseline map,
+/// shared by both the local-file and S3 code paths in [`load_baseline`].
+fn read_baseline_rows(
+    reader: impl Iterator<Item = Result<RecordBatch, arrow::error::ArrowError>>,
+) -> Result<HashMap<String, (u64, i64)>, BoxError> {
+    let mut baseline = HashMap::new();
+
+    for batch in reader {
+        let batch = batch?;
+
+        let keys = batch
+            .column_by_name("Key")
+            .ok_or("baseline manifest is missing a Key column")?
+            .as_any()
+            .downcast_ref::<StringArray>()
+            .ok_or("baseline Key column has an unexpected type")?;
+        let sizes = batch
+            .column_by_name("Size")
+            .ok_or("baseline manifest is missing a Size column")?
+            .as_any()
+            .downcast_ref::<UInt64Array>()
+            .ok_or("baseline Size column has an unexpected type")?;
+        let last_modified = batch
+            .column_by_name("LastModified")
+            .ok_or("baseline manifest is missing a LastModified column")?
+            .as_any()
+            .downcast_ref::<TimestampMillisecondArray>()
+            .ok_or("baseline LastModified column has an unexpected type")?;
+
+        for i in 0..batch.num_rows() {
+            baseline.insert(keys.value(i).to_string(), (sizes.value(i), last_modified.value(i)));
+        }
+    }
+
+    Ok(baseline)
+}
+
+/// Combines all `--min-size`/`--max-size`/`--modified-after`/`--modified-before`/
+/// `--name-glob`/`--name-regex`/`--key-regex` predicates with AND semantics.
+#[derive(Default)]
+struct ObjectFilter {
+    min_size: Option<u64>,
+    max_size: Option<u64>,
+    modified_after: Option<DateTime<Utc>>,
+    modified_before: Option<DateTime<Utc>>,
+    name_glob: Option<Pattern>,
+    name_regex: Option<Regex>,
+    key_regex: Option<Regex>,
+}
+
+impl ObjectFilter {
+    fn from_args(args: &Args) -> Result<Self, BoxError> {
+        Ok(ObjectFilter {
+            min_size: args.min_size,
+            max_size: args.max_size,
+            modified_after: args
+                .modified_after
+                .as_deref()
+                .map(parse_time_bound)
+                .transpose()?,
+            modified_before: args
+                .modified_before
+                .as_deref()
+                .map(parse_time_bound)
+                .transpose()?,
+            name_glob: args.name_glob.as_deref().map(Pattern::new).transpose()?,
+            name_regex: args.name_regex.as_deref().map(Regex::new).transpose()?,
+            key_regex: args.key_regex.as_deref().map(Regex::new).transpose()?,
+        })
+    }
+
+    fn matches(&self, key: &str, file_name: &str, size: u64, last_modified_millis: i64) -> bool {
+        if let Some(min_size) = self.min_size {
+            if size < min_size {
+                return false;
+            }
+        }
+        if let Some(max_size) = self.max_size {
+            if size > max_size {
+                return false;
+            }
+        }
+        if let Some(after) = self.modified_after {
+            if last_modified_millis < after.timestamp_millis() {
+                return false;
+            }
+        }
+        if let Some(before) = self.modified_before {
+            if last_modified_millis > before.timestamp_millis() {
+                return false;
+            }
+        }
+        if let Some(glob) = &self.name_glob {
+            if !glob.matches(file_name) {
+                return false;
+            }
+        }
+        if let Some(regex) = &self.name_regex {
+            if !regex.is_match(file_name) {
+                return false;
+            }
+        }
+        if let Some(regex) = &self.key_regex {
+            if !regex.is_match(key) {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+/// Parses an RFC3339 timestamp, or a relative duration like "7d"/"12h"/"30m"/"45s"
+/// measured back from now.
+fn parse_time_bound(value: &str) -> Result<DateTime<Utc>, BoxError> {
+    if let Some(duration) = parse_relative_duration(value) {
+        return Ok(Utc::now() - duration);
+    }
+
+    DateTime::parse_from_rfc3339(value)
+        .map(|dt| dt.with_timezone(&Utc))
+        .map_err(|e| format!("invalid time value {:?}: {}", value, e).into())
+}
+
+fn parse_relative_duration(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    let unit = value.chars().next_back()?;
+    let amount = &value[..value.len() - unit.len_utf8()];
+    let amount: i64 = amount.parse().ok()?;
+
+    match unit {
+        'd' => Some(Duration::days(amount)),
+        'h' => Some(Duration::hours(amount)),
+        'm' => Some(Duration::minutes(amount)),
+        's' => Some(Duration::seconds(amount)),
+        _ => None,
+    }
+}
+
+/// Shared state for the bounded worker pool that walks the prefix tree. Every prefix is
+/// popped from `queue` by exactly one worker, so no two workers ever scan the same keys.
+struct FanoutState {
+    queue: AsyncMutex<VecDeque<String>>,
+    pending: AtomicUsize,
+    notify: Notify,
+}
+
+impl FanoutState {
+    fn new(initial: Vec<String>) -> Self {
+        let pending = AtomicUsize::new(initial.len());
+        FanoutState {
+            queue: AsyncMutex::new(VecDeque::from(initial)),
+            pending,
+            notify: Notify::new(),
+        }
+    }
+
+    async fn push(&self, prefixes: Vec<String>) {
+        if prefixes.is_empty() {
+            return;
+        }
+        self.pending.fetch_add(prefixes.len(), Ordering::SeqCst);
+        self.queue.lock().await.extend(prefixes);
+        self.notify.notify_waiters();
+    }
+
+    /// Pops the next prefix to scan, or returns `None` once no prefix is queued or in
+    /// flight anywhere in the pool.
+    async fn pop(&self) -> Option<String> {
+        loop {
+            // Registered before the queue/pending check (per `Notify`'s documented
+            // pattern) so a `notify_waiters()` firing between the check and the await
+            // below can't be missed: `Notified` only misses wakeups from calls that
+            // happened before it was created.
+            let notified = self.notify.notified();
+
+            if let Some(prefix) = self.queue.lock().await.pop_front() {
+                return Some(prefix);
+            }
+            if self.pending.load(Ordering::SeqCst) == 0 {
+                return None;
+            }
+            notified.await;
+        }
+    }
+
+    fn mark_done(&self) {
+        if self.pending.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.notify.notify_waiters();
+        }
+    }
+}
+
+/// An object as it comes off the listing, plus its tag set when `--fetch-tags` asked for
+/// one. Bundling the two keeps the channel payload self-contained for the writer task.
+struct ScannedObject {
+    object: Object,
+    tags: Option<String>,
+}
+
+/// Fetches an object's tag set and serializes it as comma-separated `key=value` pairs, or
+/// `None` if it has no tags.
+async fn fetch_object_tags(
+    s3_client: &S3Client,
+    bucket_name: &str,
+    key: &str,
+) -> Result<Option<String>, BoxError> {
+    let retry_strategy = ExponentialBackoff::from_millis(100).map(jitter).take(3);
+
+    let response = Retry::spawn(retry_strategy, || {
+        let s3_client = s3_client.clone();
+        async move {
+            s3_client
+                .get_object_tagging()
+                .bucket(bucket_name)
+                .key(key)
+                .send()
+                .await
+                .map_err(|e| {
+                    println!("Error fetching tags for {}, retrying: {:?}", key, e);
+                    e
+                })
+        }
+    })
+    .await?;
+
+    if response.tag_set().is_empty() {
+        return Ok(None);
+    }
+
+    Ok(Some(
+        response
+            .tag_set()
+            .iter()
+            .map(|tag| format!("{}={}", tag.key(), tag.value()))
+            .collect::<Vec<_>>()
+            .join(","),
+    ))
+}
+
+/// Lists a single prefix "directory" (i.e. one delimiter level) to completion, sending
+/// every direct object to `tx` and returning the sub-prefixes discovered beneath it. When
+/// `fetch_tags` is set, each page's objects have their tags fetched concurrently, bounded
+/// by [`TAG_FETCH_CONCURRENCY`], rather than one at a time.
+async fn list_level(
+    s3_client: &S3Client,
+    bucket_name: &str,
+    prefix: &str,
+    delimiter: &str,
+    fetch_tags: bool,
+    tx: &UnboundedSender<ScannedObject>,
+) -> Result<(usize, Vec<String>), BoxError> {
+    let retry_strategy = ExponentialBackoff::from_millis(100).map(jitter).take(3);
+    let mut continuation_token: Option<String> = None;
+    let mut sub_prefixes = Vec::new();
+    let mut key_count = 0usize;
+
+    loop {
+        let continuation_token_for_request = continuation_token.clone();
+
+        let result = Retry::spawn(retry_strategy.clone(), || {
+            let s3_client = s3_client.clone();
+            let continuation_token = continuation_token_for_request.clone();
+            async move {
+                s3_client
+                    .list_objects_v2()
+                    .bucket(bucket_name)
+                    .prefix(prefix)
+                    .delimiter(delimiter)
+                    .set_continuation_token(continuation_token)
+                    .max_keys(1000)
+                    .send()
+                    .await
+                    .map_err(|e| {
+                        println!("Error listing objects, retrying: {:?}", e);
+                        e
+                    })
+            }
+        })
+        .await?;
+
+        key_count += result.contents().len();
+
+        if fetch_tags {
+            let mut tag_fetches = stream::iter(result.contents().to_vec())
+                .map(|object| async move {
+                    let tags =
+                        fetch_object_tags(s3_client, bucket_name, object.key().unwrap_or("")).await?;
+                    Ok::<ScannedObject, BoxError>(ScannedObject { object, tags })
+                })
+                .buffer_unordered(TAG_FETCH_CONCURRENCY);
+
+            while let Some(scanned) = tag_fetches.next().await {
+                tx.send(scanned?)
+                    .map_err(|_| "manifest writer task ended unexpectedly")?;
+            }
+        } else {
+            for object in result.contents() {
+                tx.send(ScannedObject { object: object.clone(), tags: None })
+                    .map_err(|_| "manifest writer task ended unexpectedly")?;
+            }
+        }
+
+        sub_prefixes.extend(
+            result
+                .common_prefixes()
+                .iter()
+                .filter_map(|cp| cp.prefix().map(str::to_string)),
+        );
+
+        continuation_token = result.next_continuation_token().map(str::to_string);
+        if !result.is_truncated().unwrap_or(false) {
+            break;
+        }
+    }
+
+    Ok((key_count, sub_prefixes))
+}
+
+/// Recursively walks `prefix`, splitting work back to the shared queue only once a
+/// single worker's own level has reached [`SPLIT_THRESHOLD`]; smaller sub-prefixes are
+/// walked inline so the pool isn't flooded with tasks for shallow trees.
+fn scan_prefix<'a>(
+    s3_client: &'a S3Client,
+    bucket_name: &'a str,
+    prefix: String,
+    delimiter: &'a str,
+    fetch_tags: bool,
+    tx: &'a UnboundedSender<ScannedObject>,
+    state: &'a Arc<FanoutState>,
+) -> Pin<Box<dyn Future<Output = Result<(), BoxError>> + Send + 'a>> {
+    Box::pin(async move {
+        let (key_count, sub_prefixes) =
+            list_level(s3_client, bucket_name, &prefix, delimiter, fetch_tags, tx).await?;
+
+        if sub_prefixes.is_empty() {
+            return Ok(());
+        }
+
+        if key_count >= SPLIT_THRESHOLD {
+            state.push(sub_prefixes).await;
+        } else {
+            for sub_prefix in sub_prefixes {
+                scan_prefix(s3_client, bucket_name, sub_prefix, delimiter, fetch_tags, tx, state).await?;
+            }
+        }
+
+        Ok(())
+    })
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn run_fanout_scan(
+    s3_client: S3Client,
+    bucket_name: String,
+    delimiter: String,
+    fetch_tags: bool,
+    root_prefixes: Vec<String>,
+    tx: UnboundedSender<ScannedObject>,
+    concurrency: usize,
+) -> Result<(), BoxError> {
+    let state = Arc::new(FanoutState::new(root_prefixes));
+
+    let mut workers = Vec::with_capacity(concurrency);
+    for _ in 0..concurrency {
+        let s3_client = s3_client.clone();
+        let bucket_name = bucket_name.clone();
+        let delimiter = delimiter.clone();
+        let tx = tx.clone();
+        let state = state.clone();
+        workers.push(tokio::spawn(async move {
+            while let Some(prefix) = state.pop().await {
+                let result =
+                    scan_prefix(&s3_client, &bucket_name, prefix, &delimiter, fetch_tags, &tx, &state).await;
+                state.mark_done();
+                result?;
+            }
+            Ok::<(), BoxError>(())
+        }));
+    }
+
+    for worker in workers {
+        worker.await??;
+    }
+
+    Ok(())
+}
+
+/// Builds the manifest's Arrow schema. `ETag` and `StorageClass` come for free from the
+/// list response and are always included; `Tags` is added only when `--fetch-tags` was
+/// passed, and `ChangeType` only in `--baseline` diff mode, so the default output format
+/// is unaffected by either.
+fn build_schema(diff_mode: bool, fetch_tags: bool) -> Arc<Schema> {
+    let mut fields = vec![
+        Field::new("Bucket", DataType::Utf8, false),
+        Field::new("Key", DataType::Utf8, false),
+        Field::new("FileName", DataType::Utf8, false),
+        Field::new("Size", DataType::UInt64, false),
+        Field::new(
+            "LastModified",
+            DataType::Timestamp(arrow::datatypes::TimeUnit::Millisecond, None),
+            false,
+        ),
+        Field::new("ETag", DataType::Utf8, true),
+        Field::new("StorageClass", DataType::Utf8, true),
+    ];
+
+    if fetch_tags {
+        fields.push(Field::new("Tags", DataType::Utf8, true));
+    }
+    if diff_mode {
+        fields.push(Field::new("ChangeType", DataType::Utf8, false));
+    }
+
+    Arc::new(Schema::new(fields))
+}
+
+#[allow(clippy::too_many_arguments)]
 async fn generate_manifest(
     bucket_name: &str,
     output_file: &str,
@@ -124,27 +642,29 @@ async fn generate_manifest(
     dest_endpoint: Option<String>,
     prefix: Option<String>,
     delimiter: &str,
+    concurrency: usize,
+    filter: ObjectFilter,
+    baseline: Option<String>,
+    changes_only: bool,
+    fetch_tags: bool,
     output_bucket: Option<String>,
     output_key: String,
     source_access_key: Option<String>,
     source_secret_key: Option<String>,
     dest_access_key: Option<String>,
     dest_secret_key: Option<String>,
-) -> Result<(), Box<dyn Error>> {
-    let s3_client = create_s3_client(source_endpoint, source_access_key, source_secret_key)?;
-    let schema = Arc::new(Schema::new(vec![
-        Field::new("Bucket", DataType::Utf8, false),
-        Field::new("Key", DataType::Utf8, false),
-        Field::new("FileName", DataType::Utf8, false),
-        Field::new("Size", DataType::UInt64, false),
-        Field::new(
-            "LastModified",
-            DataType::Timestamp(arrow::datatypes::TimeUnit::Millisecond, None),
-            false,
-        ),
-    ]));
+) -> Result<(), BoxError> {
+    let s3_client = create_s3_client(source_endpoint, source_access_key, source_secret_key).await?;
+
+    let baseline = match baseline {
+        Some(location) => Some(load_baseline(&s3_client, &location).await?),
+        None => None,
+    };
+    let diff_mode = baseline.is_some();
 
-    let (mut writer, temp_file) = if output_bucket.is_some() {
+    let schema = build_schema(diff_mode, fetch_tags);
+
+    let (writer, temp_file) = if output_bucket.is_some() {
         let temp_file = NamedTempFile::new()?;
         let props = WriterProperties::builder().build();
         let writer = ArrowWriter::try_new(
@@ -164,15 +684,6 @@ async fn generate_manifest(
         (writer, None)
     };
 
-    let mut continuation_token: Option<String> = None;
-    let mut bucket_builder = StringBuilder::new();
-    let mut key_builder = StringBuilder::new();
-    let mut file_name_builder = StringBuilder::new();
-    let mut size_builder = UInt64Builder::new();
-    let mut last_modified_builder = TimestampMillisecondBuilder::new();
-
-    let retry_strategy = ExponentialBackoff::from_millis(100).map(jitter).take(3);
-
     let pb = ProgressBar::new_spinner();
     pb.set_style(
         ProgressStyle::default_spinner()
@@ -182,96 +693,150 @@ async fn generate_manifest(
     pb.set_length(0);
 
     let start_time = Instant::now();
-    let mut total_objects = 0;
+    let root_prefix = prefix.clone().unwrap_or_default();
 
-    loop {
-        let request = ListObjectsV2Request {
-            bucket: bucket_name.to_string(),
-            prefix: prefix.clone(),
-            continuation_token: continuation_token.clone(),
-            max_keys: Some(1000),
-            ..Default::default()
-        };
+    let (tx, mut rx) = mpsc::unbounded_channel::<ScannedObject>();
+    let bucket_name_owned = bucket_name.to_string();
+    let delimiter_owned = delimiter.to_string();
 
-        let result = Retry::spawn(retry_strategy.clone(), || {
-            let s3_client = s3_client.clone();
-            let request = request.clone();
-            async move {
-                s3_client.list_objects_v2(request).await.map_err(|e| {
-                    println!("Error listing objects, retrying: {:?}", e);
-                    e
-                })
-            }
-        })
-        .await?;
+    // The writer task is the sole owner of the Arrow builders and the progress bar, so
+    // counts and row order stay consistent no matter how many workers are feeding it.
+    let writer_task = tokio::spawn(async move {
+        let bucket_name = bucket_name_owned;
+        let delimiter = delimiter_owned;
+        let mut writer = writer;
+        let mut baseline = baseline;
+        let mut builders = ManifestBuilders::new(fetch_tags, diff_mode);
+        let mut total_scanned: u64 = 0;
+        let mut total_matched: u64 = 0;
+
+        while let Some(scanned) = rx.recv().await {
+            total_scanned += 1;
+            pb.set_position(total_scanned);
+
+            let object = scanned.object;
+            let key = object.key().unwrap_or("").to_string();
+            let file_name = key.rsplit(&delimiter).next().unwrap_or(&key).to_string();
+            let size = object.size().unwrap_or(0) as u64;
+            let last_modified_millis = object
+                .last_modified()
+                .and_then(|dt| dt.to_millis().ok())
+                .unwrap_or(0);
+            let e_tag = object.e_tag().map(str::to_string);
+            let storage_class = object.storage_class().map(|sc| sc.as_str().to_string());
 
-        if let Some(objects) = result.contents {
-            for object in objects {
-                if let Some(ref prefix) = prefix {
-                    if !object
-                        .key
-                        .as_ref()
-                        .unwrap_or(&String::new())
-                        .starts_with(prefix)
+            // Removing the key (rather than just looking it up) doubles as marking it
+            // "seen": whatever is left in `baseline` once the listing ends is Deleted.
+            let change_type = baseline.as_mut().map(|baseline| {
+                match baseline.remove(&key) {
+                    None => "Added",
+                    Some((base_size, base_last_modified))
+                        if base_size != size || base_last_modified != last_modified_millis =>
                     {
-                        continue;
+                        "Modified"
                     }
+                    Some(_) => "Unchanged",
                 }
+            });
 
-                add_object_to_builders(
-                    bucket_name,
-                    &object,
-                    delimiter,
-                    &mut bucket_builder,
-                    &mut key_builder,
-                    &mut file_name_builder,
-                    &mut size_builder,
-                    &mut last_modified_builder,
-                )?;
-                total_objects += 1;
-                pb.set_position(total_objects);
+            if !filter.matches(&key, &file_name, size, last_modified_millis) {
+                continue;
             }
-        }
+            if changes_only && change_type == Some("Unchanged") {
+                continue;
+            }
+            total_matched += 1;
+
+            builders.append(&ManifestRow {
+                bucket_name: &bucket_name,
+                key: &key,
+                file_name: &file_name,
+                size,
+                last_modified_millis,
+                e_tag: e_tag.as_deref(),
+                storage_class: storage_class.as_deref(),
+                tags: scanned.tags.as_deref(),
+                change_type,
+            });
 
-        if key_builder.len() >= 1000 {
-            write_batch(
-                &mut writer,
-                &schema,
-                &mut bucket_builder,
-                &mut key_builder,
-                &mut file_name_builder,
-                &mut size_builder,
-                &mut last_modified_builder,
-            )?;
+            if builders.len() >= 1000 {
+                builders.write_batch(&mut writer, &schema)?;
+
+                let elapsed = start_time.elapsed();
+                let objects_per_second = total_scanned as f64 / elapsed.as_secs_f64();
+                pb.set_message(format!("{:.2} objects/sec", objects_per_second));
+            }
         }
 
-        continuation_token = result.next_continuation_token;
+        // Anything left in the baseline map never showed up in the fresh listing. It has
+        // no fresh ETag/StorageClass/Tags, so those columns are left null for these rows.
+        if let Some(baseline) = baseline {
+            for (key, (size, last_modified_millis)) in baseline {
+                let file_name = key.rsplit(&delimiter).next().unwrap_or(&key).to_string();
+                if !filter.matches(&key, &file_name, size, last_modified_millis) {
+                    continue;
+                }
 
-        if !result.is_truncated.unwrap_or(false) {
-            break;
+                builders.append(&ManifestRow {
+                    bucket_name: &bucket_name,
+                    key: &key,
+                    file_name: &file_name,
+                    size,
+                    last_modified_millis,
+                    e_tag: None,
+                    storage_class: None,
+                    tags: None,
+                    change_type: Some("Deleted"),
+                });
+
+                if builders.len() >= 1000 {
+                    builders.write_batch(&mut writer, &schema)?;
+                }
+            }
         }
 
+        if builders.len() > 0 {
+            builders.write_batch(&mut writer, &schema)?;
+        }
+
+        writer.close()?;
+
         let elapsed = start_time.elapsed();
-        let objects_per_second = total_objects as f64 / elapsed.as_secs_f64();
-        pb.set_message(format!("{:.2} objects/sec", objects_per_second));
-    }
-
-    if key_builder.len() > 0 {
-        write_batch(
-            &mut writer,
-            &schema,
-            &mut bucket_builder,
-            &mut key_builder,
-            &mut file_name_builder,
-            &mut size_builder,
-            &mut last_modified_builder,
-        )?;
-    }
+        pb.finish_with_message(format!(
+            "Done. Matched {} of {} objects scanned in {:.2?} ({:.2} objects/sec)",
+            total_matched,
+            total_scanned,
+            elapsed,
+            total_scanned as f64 / elapsed.as_secs_f64()
+        ));
+
+        Ok::<(u64, u64), BoxError>((total_scanned, total_matched))
+    });
+
+    let (root_key_count, root_sub_prefixes) =
+        list_level(&s3_client, bucket_name, &root_prefix, delimiter, fetch_tags, &tx).await?;
+    let _ = root_key_count;
+
+    run_fanout_scan(
+        s3_client,
+        bucket_name.to_string(),
+        delimiter.to_string(),
+        fetch_tags,
+        root_sub_prefixes,
+        tx.clone(),
+        concurrency,
+    )
+    .await?;
+
+    // Every worker has finished and dropped its clone by now, so this is the last sender;
+    // dropping it closes the writer task's channel.
+    drop(tx);
 
-    writer.close()?;
+    let (total_scanned, total_matched) = writer_task.await??;
+    let _ = (total_scanned, total_matched);
 
     if let Some(temp_file) = temp_file {
-        let dest_s3_client = create_s3_client(dest_endpoint, dest_access_key, dest_secret_key)?;
+        let dest_s3_client = create_s3_client(dest_endpoint, dest_access_key, dest_secret_key).await?;
         upload_to_s3(
             &dest_s3_client,
             temp_file,
@@ -281,76 +846,93 @@ async fn generate_manifest(
         .await?;
     }
 
-    let elapsed = start_time.elapsed();
-    let objects_per_second = total_objects as f64 / elapsed.as_secs_f64();
-    pb.finish_with_message(format!(
-        "Done. Processed {} objects in {:.2?} ({:.2} objects/sec)",
-        total_objects, elapsed, objects_per_second
-    ));
-
     Ok(())
 }
 
-fn create_s3_client(
+/// Builds an S3 client backed by the full default credential chain (environment, shared
+/// profile files, IMDS/container credentials, SSO, and AssumeRoleWithWebIdentity for
+/// IRSA), unless `access_key`/`secret_key` are given, in which case they take priority.
+async fn create_s3_client(
     endpoint: Option<String>,
     access_key: Option<String>,
     secret_key: Option<String>,
-) -> Result<S3Client, Box<dyn Error>> {
-    let region = match endpoint {
-        Some(endpoint_url) => Region::Custom {
-            name: "custom".to_string(),
-            endpoint: endpoint_url,
-        },
-        None => Region::default(),
-    };
+) -> Result<S3Client, BoxError> {
+    let mut config_loader = aws_config::defaults(BehaviorVersion::latest());
 
-    match (access_key, secret_key) {
-        (Some(access_key), Some(secret_key)) => Ok(S3Client::new_with(
-            HttpClient::new()?,
-            StaticProvider::new_minimal(access_key, secret_key),
-            region,
-        )),
-        _ => Ok(S3Client::new_with(
-            HttpClient::new()?,
-            ChainProvider::new(),
-            region,
-        )),
+    if let (Some(access_key), Some(secret_key)) = (access_key, secret_key) {
+        let credentials = Credentials::new(access_key, secret_key, None, None, "s3-manifest-static");
+        config_loader = config_loader.credentials_provider(SharedCredentialsProvider::new(credentials));
     }
+
+    let sdk_config = config_loader.load().await;
+    let mut s3_config = aws_sdk_s3::config::Builder::from(&sdk_config);
+
+    if let Some(endpoint_url) = endpoint {
+        s3_config = s3_config.endpoint_url(endpoint_url).force_path_style(true);
+
+        // A bare S3-compatible service (e.g. MinIO/Ceph with no AWS_REGION, profile, or
+        // IMDS available) leaves the credential chain with no region to sign requests
+        // against. SigV4 still needs *some* region string, so fall back to a fixed one.
+        if sdk_config.region().is_none() {
+            s3_config = s3_config.region(Region::new("custom"));
+        }
+    }
+
+    Ok(S3Client::from_conf(s3_config.build()))
 }
 
+/// Size of each part in a multipart upload. S3 requires at least 5 MiB per part except
+/// the last, so this must stay comfortably above that floor.
+const MULTIPART_PART_SIZE: usize = 8 * 1024 * 1024;
+
+/// Files smaller than this are sent with a single `PutObject` instead of going through
+/// the multipart dance, matching S3's own 5 MiB minimum part size.
+const MULTIPART_MIN_SIZE: u64 = 5 * 1024 * 1024;
+
 async fn upload_to_s3(
     s3_client: &S3Client,
     temp_file: tempfile::NamedTempFile,
     bucket: &str,
     key: &str,
-) -> Result<(), Box<dyn Error>> {
-    let mut file = temp_file.reopen()?;
-    let mut contents = Vec::new();
-    file.read_to_end(&mut contents)?;
+) -> Result<(), BoxError> {
+    let file_len = temp_file.as_file().metadata()?.len();
 
-    let retry_strategy = ExponentialBackoff::from_millis(100).map(jitter).take(3);
+    if file_len < MULTIPART_MIN_SIZE {
+        let mut file = temp_file.reopen()?;
+        let mut contents = Vec::new();
+        file.read_to_end(&mut contents)?;
+        return put_object(s3_client, bucket, key, contents).await;
+    }
 
-    // Create a Vec<u8> from the contents
-    let contents_vec = contents.to_vec();
+    multipart_upload(s3_client, &temp_file, bucket, key).await
+}
+
+async fn put_object(
+    s3_client: &S3Client,
+    bucket: &str,
+    key: &str,
+    contents: Vec<u8>,
+) -> Result<(), BoxError> {
+    let retry_strategy = ExponentialBackoff::from_millis(100).map(jitter).take(3);
 
-    let _result = Retry::spawn(retry_strategy, || {
+    Retry::spawn(retry_strategy, || {
         let s3_client = s3_client.clone();
         let bucket = bucket.to_string();
         let key = key.to_string();
-        let contents = contents_vec.clone();
+        let contents = contents.clone();
 
         async move {
-            let put_request = PutObjectRequest {
-                bucket: bucket,
-                key: key,
-                body: Some(ByteStream::from(contents)),
-                ..Default::default()
-            };
-
-            s3_client.put_object(put_request).await.map_err(|e| {
-                println!("Error uploading object, retrying: {:?}", e);
-                e
-            })
+            s3_client
+                .put_object()
+                .bucket(bucket)
+                .key(key)
+                .body(ByteStream::from(contents))
+                .send()
+                .await
+                .map_err(|e| {
+                    println!("Error uploading object, retrying: {:?}", e);
+                    e
+                })
         }
     })
     .await?;
@@ -358,64 +940,340 @@ async fn upload_to_s3(
     Ok(())
 }
 
-fn add_object_to_builders(
-    bucket_name: &str,
-    object: &Object,
-    delimiter: &str,
-    bucket_builder: &mut StringBuilder,
-    key_builder: &mut StringBuilder,
-    file_name_builder: &mut StringBuilder,
-    size_builder: &mut UInt64Builder,
-    last_modified_builder: &mut TimestampMillisecondBuilder,
-) -> Result<(), Box<dyn Error>> {
-    bucket_builder.append_value(bucket_name);
-
-    let key = object.key.as_deref().unwrap_or("");
-    key_builder.append_value(key);
-
-    let file_name = key.rsplit(delimiter).next().unwrap_or(key);
-    file_name_builder.append_value(file_name);
-
-    size_builder.append_value(object.size.unwrap_or(0) as u64);
-
-    let last_modified = object
-        .last_modified
-        .as_ref()
-        .and_then(|s| DateTime::parse_from_rfc3339(s).ok())
-        .map(|dt| dt.with_timezone(&Utc).timestamp_millis())
-        .unwrap_or(0);
-    last_modified_builder.append_value(last_modified);
+/// Uploads `temp_file` as a multipart upload, streaming it in [`MULTIPART_PART_SIZE`]
+/// chunks so the whole file never has to sit in memory at once. Aborts the upload (so no
+/// orphaned parts accrue storage cost) if any part fails after its own retries.
+async fn multipart_upload(
+    s3_client: &S3Client,
+    temp_file: &tempfile::NamedTempFile,
+    bucket: &str,
+    key: &str,
+) -> Result<(), BoxError> {
+    let create = s3_client
+        .create_multipart_upload()
+        .bucket(bucket)
+        .key(key)
+        .send()
+        .await?;
+    let upload_id = create
+        .upload_id()
+        .ok_or("S3 did not return an upload ID for the multipart upload")?
+        .to_string();
 
-    Ok(())
+    let result = match upload_parts(s3_client, temp_file, bucket, key, &upload_id).await {
+        Ok(completed_parts) => {
+            s3_client
+                .complete_multipart_upload()
+                .bucket(bucket)
+                .key(key)
+                .upload_id(&upload_id)
+                .multipart_upload(
+                    CompletedMultipartUpload::builder()
+                        .set_parts(Some(completed_parts))
+                        .build(),
+                )
+                .send()
+                .await
+                .map(|_| ())
+                .map_err(BoxError::from)
+        }
+        Err(e) => Err(e),
+    };
+
+    if result.is_err() {
+        let _ = s3_client
+            .abort_multipart_upload()
+            .bucket(bucket)
+            .key(key)
+            .upload_id(&upload_id)
+            .send()
+            .await;
+    }
+
+    result
 }
 
-fn write_batch(
-    writer: &mut ArrowWriter<Box<dyn std::io::Write + Send>>,
-    schema: &Arc<Schema>,
-    bucket_builder: &mut StringBuilder,
-    key_builder: &mut StringBuilder,
-    file_name_builder: &mut StringBuilder,
-    size_builder: &mut UInt64Builder,
-    last_modified_builder: &mut TimestampMillisecondBuilder,
-) -> Result<(), Box<dyn Error>> {
-    let batch = RecordBatch::try_new(
-        schema.clone(),
-        vec![
-            Arc::new(bucket_builder.finish()),
-            Arc::new(key_builder.finish()),
-            Arc::new(file_name_builder.finish()),
-            Arc::new(size_builder.finish()),
-            Arc::new(last_modified_builder.finish()),
-        ],
-    )?;
-
-    writer.write(&batch)?;
-
-    bucket_builder.finish();
-    key_builder.finish();
-    file_name_builder.finish();
-    size_builder.finish();
-    last_modified_builder.finish();
+async fn upload_parts(
+    s3_client: &S3Client,
+    temp_file: &tempfile::NamedTempFile,
+    bucket: &str,
+    key: &str,
+    upload_id: &str,
+) -> Result<Vec<CompletedPart>, BoxError> {
+    let mut file = temp_file.reopen()?;
+    let mut completed_parts = Vec::new();
+    let mut part_number: i32 = 1;
 
-    Ok(())
+    loop {
+        let mut buffer = vec![0u8; MULTIPART_PART_SIZE];
+        let mut filled = 0;
+        while filled < buffer.len() {
+            let n = file.read(&mut buffer[filled..])?;
+            if n == 0 {
+                break;
+            }
+            filled += n;
+        }
+        if filled == 0 {
+            break;
+        }
+        buffer.truncate(filled);
+
+        let retry_strategy = ExponentialBackoff::from_millis(100).map(jitter).take(3);
+        let response = Retry::spawn(retry_strategy, || {
+            let s3_client = s3_client.clone();
+            let bucket = bucket.to_string();
+            let key = key.to_string();
+            let upload_id = upload_id.to_string();
+            let buffer = buffer.clone();
+
+            async move {
+                s3_client
+                    .upload_part()
+                    .bucket(bucket)
+                    .key(key)
+                    .upload_id(upload_id)
+                    .part_number(part_number)
+                    .body(ByteStream::from(buffer))
+                    .send()
+                    .await
+                    .map_err(|e| {
+                        println!("Error uploading part {}, retrying: {:?}", part_number, e);
+                        e
+                    })
+            }
+        })
+        .await?;
+
+        let e_tag = response
+            .e_tag()
+            .ok_or("S3 did not return an ETag for the uploaded part")?
+            .to_string();
+
+        completed_parts.push(
+            CompletedPart::builder()
+                .e_tag(e_tag)
+                .part_number(part_number)
+                .build(),
+        );
+
+        part_number += 1;
+        if filled < MULTIPART_PART_SIZE {
+            break;
+        }
+    }
+
+    Ok(completed_parts)
+}
+
+/// One row's worth of manifest data, already extracted from the `Object`/tags/baseline
+/// lookup. Rows are only built for objects that already passed [`ObjectFilter::matches`],
+/// and are then committed with [`ManifestBuilders::append`].
+struct ManifestRow<'a> {
+    bucket_name: &'a str,
+    key: &'a str,
+    file_name: &'a str,
+    size: u64,
+    last_modified_millis: i64,
+    e_tag: Option<&'a str>,
+    storage_class: Option<&'a str>,
+    tags: Option<&'a str>,
+    change_type: Option<&'a str>,
+}
+
+/// Owns every column builder for the manifest, plus the optional `Tags`/`ChangeType`
+/// builders that only exist when `--fetch-tags`/`--baseline` are in play. Bundling these
+/// keeps the writer task's per-object loop and flush logic to a single call each.
+struct ManifestBuilders {
+    bucket: StringBuilder,
+    key: StringBuilder,
+    file_name: StringBuilder,
+    size: UInt64Builder,
+    last_modified: TimestampMillisecondBuilder,
+    e_tag: StringBuilder,
+    storage_class: StringBuilder,
+    tags: Option<StringBuilder>,
+    change_type: Option<StringBuilder>,
+}
+
+impl ManifestBuilders {
+    fn new(fetch_tags: bool, diff_mode: bool) -> Self {
+        ManifestBuilders {
+            bucket: StringBuilder::new(),
+            key: StringBuilder::new(),
+            file_name: StringBuilder::new(),
+            size: UInt64Builder::new(),
+            last_modified: TimestampMillisecondBuilder::new(),
+            e_tag: StringBuilder::new(),
+            storage_class: StringBuilder::new(),
+            tags: fetch_tags.then(StringBuilder::new),
+            change_type: diff_mode.then(StringBuilder::new),
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.key.len()
+    }
+
+    fn append(&mut self, row: &ManifestRow) {
+        self.bucket.append_value(row.bucket_name);
+        self.key.append_value(row.key);
+        self.file_name.append_value(row.file_name);
+        self.size.append_value(row.size);
+        self.last_modified.append_value(row.last_modified_millis);
+
+        match row.e_tag {
+            Some(e_tag) => self.e_tag.append_value(e_tag),
+            None => self.e_tag.append_null(),
+        }
+        match row.storage_class {
+            Some(storage_class) => self.storage_class.append_value(storage_class),
+            None => self.storage_class.append_null(),
+        }
+
+        if let Some(tags_builder) = self.tags.as_mut() {
+            match row.tags {
+                Some(tags) => tags_builder.append_value(tags),
+                None => tags_builder.append_null(),
+            }
+        }
+        if let Some(change_type_builder) = self.change_type.as_mut() {
+            change_type_builder.append_value(row.change_type.unwrap_or_default());
+        }
+    }
+
+    fn write_batch(
+        &mut self,
+        writer: &mut ArrowWriter<Box<dyn std::io::Write + Send>>,
+        schema: &Arc<Schema>,
+    ) -> Result<(), BoxError> {
+        let mut columns: Vec<Arc<dyn Array>> = vec![
+            Arc::new(self.bucket.finish()),
+            Arc::new(self.key.finish()),
+            Arc::new(self.file_name.finish()),
+            Arc::new(self.size.finish()),
+            Arc::new(self.last_modified.finish()),
+            Arc::new(self.e_tag.finish()),
+            Arc::new(self.storage_class.finish()),
+        ];
+
+        if let Some(tags_builder) = self.tags.as_mut() {
+            columns.push(Arc::new(tags_builder.finish()));
+        }
+        if let Some(change_type_builder) = self.change_type.as_mut() {
+            columns.push(Arc::new(change_type_builder.finish()));
+        }
+
+        let batch = RecordBatch::try_new(schema.clone(), columns)?;
+        writer.write(&batch)?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_relative_duration_accepts_supported_units() {
+        assert_eq!(parse_relative_duration("7d"), Some(Duration::days(7)));
+        assert_eq!(parse_relative_duration("12h"), Some(Duration::hours(12)));
+        assert_eq!(parse_relative_duration("30m"), Some(Duration::minutes(30)));
+        assert_eq!(parse_relative_duration("45s"), Some(Duration::seconds(45)));
+    }
+
+    #[test]
+    fn parse_relative_duration_rejects_unknown_unit_or_empty_input() {
+        assert_eq!(parse_relative_duration("7x"), None);
+        assert_eq!(parse_relative_duration(""), None);
+        assert_eq!(parse_relative_duration("d"), None);
+    }
+
+    #[test]
+    fn parse_relative_duration_does_not_panic_on_multi_byte_unit() {
+        assert_eq!(parse_relative_duration("5β"), None);
+    }
+
+    #[test]
+    fn parse_time_bound_falls_back_to_rfc3339() {
+        let parsed = parse_time_bound("2024-01-01T00:00:00Z").unwrap();
+        assert_eq!(parsed.timestamp(), 1704067200);
+    }
+
+    #[test]
+    fn parse_time_bound_rejects_garbage() {
+        assert!(parse_time_bound("not-a-time").is_err());
+    }
+
+    #[test]
+    fn filter_matches_everything_by_default() {
+        let filter = ObjectFilter::default();
+        assert!(filter.matches("a/b.txt", "b.txt", 0, 0));
+    }
+
+    #[test]
+    fn filter_min_size_is_inclusive() {
+        let filter = ObjectFilter {
+            min_size: Some(100),
+            ..Default::default()
+        };
+        assert!(!filter.matches("k", "k", 99, 0));
+        assert!(filter.matches("k", "k", 100, 0));
+    }
+
+    #[test]
+    fn filter_max_size_is_inclusive() {
+        let filter = ObjectFilter {
+            max_size: Some(100),
+            ..Default::default()
+        };
+        assert!(filter.matches("k", "k", 100, 0));
+        assert!(!filter.matches("k", "k", 101, 0));
+    }
+
+    #[test]
+    fn filter_modified_after_and_before_are_inclusive_bounds() {
+        let filter = ObjectFilter {
+            modified_after: Some(DateTime::from_timestamp_millis(1_000).unwrap()),
+            modified_before: Some(DateTime::from_timestamp_millis(2_000).unwrap()),
+            ..Default::default()
+        };
+        assert!(!filter.matches("k", "k", 0, 999));
+        assert!(filter.matches("k", "k", 0, 1_000));
+        assert!(filter.matches("k", "k", 0, 2_000));
+        assert!(!filter.matches("k", "k", 0, 2_001));
+    }
+
+    #[test]
+    fn filter_name_glob_matches_file_name_not_full_key() {
+        let filter = ObjectFilter {
+            name_glob: Some(Pattern::new("*.parquet").unwrap()),
+            ..Default::default()
+        };
+        assert!(filter.matches("a/b/data.parquet", "data.parquet", 0, 0));
+        assert!(!filter.matches("a/b/data.csv", "data.csv", 0, 0));
+    }
+
+    #[test]
+    fn filter_key_regex_matches_full_key() {
+        let filter = ObjectFilter {
+            key_regex: Some(Regex::new("^a/b/").unwrap()),
+            ..Default::default()
+        };
+        assert!(filter.matches("a/b/data.csv", "data.csv", 0, 0));
+        assert!(!filter.matches("c/d/data.csv", "data.csv", 0, 0));
+    }
+
+    #[test]
+    fn filter_combines_predicates_with_and() {
+        let filter = ObjectFilter {
+            min_size: Some(100),
+            name_regex: Some(Regex::new(r"\.parquet$").unwrap()),
+            ..Default::default()
+        };
+        assert!(!filter.matches("k", "data.parquet", 50, 0));
+        assert!(!filter.matches("k", "data.csv", 200, 0));
+        assert!(filter.matches("k", "data.parquet", 200, 0));
+    }
 }